@@ -1,3 +1,5 @@
+use rand::RngCore;
+use tauri::Emitter;
 use std::fs;
 use std::path::PathBuf;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
@@ -47,6 +49,186 @@ fn save_update_check_timestamp(app: &tauri::AppHandle) {
     }
 }
 
+fn get_rollout_bucket_file(app: &tauri::AppHandle) -> Option<PathBuf> {
+    app.path().app_data_dir().ok().map(|dir| dir.join("rollout_bucket"))
+}
+
+fn get_or_create_rollout_bucket(app: &tauri::AppHandle) -> u32 {
+    let Some(file_path) = get_rollout_bucket_file(app) else {
+        return 0;
+    };
+
+    if let Ok(content) = fs::read_to_string(&file_path) {
+        if let Ok(bucket) = content.trim().parse::<u32>() {
+            return bucket;
+        }
+    }
+
+    let bucket = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .subsec_nanos()
+        % 100;
+
+    if let Some(parent) = file_path.parent() {
+        let _ = fs::create_dir_all(parent);
+    }
+    if let Err(e) = fs::write(&file_path, bucket.to_string()) {
+        log::warn!("Failed to persist rollout bucket: {}", e);
+    }
+
+    bucket
+}
+
+fn is_version_newer(candidate_version: &str, current_version: &str) -> bool {
+    match (semver::Version::parse(candidate_version), semver::Version::parse(current_version)) {
+        (Ok(candidate), Ok(current)) => candidate > current,
+        _ => candidate_version != current_version,
+    }
+}
+
+/// Lets ops force downgrades across the fleet (e.g. an emergency rollback) without
+/// depending on the release manifest setting `allow_downgrade` itself.
+fn get_allow_downgrade_override() -> bool {
+    std::env::var("HACKERAI_UPDATE_ALLOW_DOWNGRADE")
+        .map(|v| v.eq_ignore_ascii_case("true") || v.trim() == "1")
+        .unwrap_or(false)
+}
+
+/// Lets ops force a specific rollout percentage across the fleet, overriding whatever
+/// the release manifest advertises (e.g. to pause or accelerate a staged rollout).
+fn get_rollout_percentage_override() -> Option<u64> {
+    std::env::var("HACKERAI_UPDATE_ROLLOUT_OVERRIDE_PERCENT")
+        .ok()
+        .and_then(|s| s.trim().parse().ok())
+}
+
+enum UpdateDecision {
+    Install,
+    RejectedNotNewer,
+    RejectedRolloutCohort,
+}
+
+fn decide_update_install(current_version: &str, candidate_version: &str, manifest: &serde_json::Value, bucket: u32) -> UpdateDecision {
+    let allow_downgrade = get_allow_downgrade_override()
+        || manifest.get("allow_downgrade").and_then(|v| v.as_bool()).unwrap_or(false);
+    if !allow_downgrade && !is_version_newer(candidate_version, current_version) {
+        return UpdateDecision::RejectedNotNewer;
+    }
+
+    let rollout_percentage =
+        get_rollout_percentage_override().or_else(|| manifest.get("rollout_percentage").and_then(|v| v.as_u64()));
+    match rollout_percentage {
+        Some(percentage) if (bucket as u64) >= percentage => UpdateDecision::RejectedRolloutCohort,
+        _ => UpdateDecision::Install,
+    }
+}
+
+const DEFAULT_UPDATE_SNOOZE_INTERVAL: Duration = Duration::from_secs(4 * 60 * 60); // 4 hours
+
+fn get_update_snooze_interval() -> Duration {
+    match std::env::var("HACKERAI_UPDATE_SNOOZE_SECONDS") {
+        Ok(secs) => secs
+            .trim()
+            .parse()
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_UPDATE_SNOOZE_INTERVAL),
+        Err(_) => DEFAULT_UPDATE_SNOOZE_INTERVAL,
+    }
+}
+
+fn get_snoozed_update_file(app: &tauri::AppHandle) -> Option<PathBuf> {
+    app.path().app_data_dir().ok().map(|dir| dir.join("snoozed_update"))
+}
+
+/// Reads the (version, re-prompt deadline) pair the user last declined, if any.
+fn read_snoozed_update(app: &tauri::AppHandle) -> Option<(String, u64)> {
+    let file_path = get_snoozed_update_file(app)?;
+    let content = fs::read_to_string(&file_path).ok()?;
+    let mut lines = content.lines();
+    let version = lines.next()?.trim().to_string();
+    let deadline: u64 = lines.next()?.trim().parse().ok()?;
+    Some((version, deadline))
+}
+
+/// Returns true if `version` was declined by the user and its snooze window hasn't elapsed yet.
+fn is_update_snoozed(app: &tauri::AppHandle, version: &str) -> bool {
+    let Some((snoozed_version, deadline)) = read_snoozed_update(app) else {
+        return false;
+    };
+    if snoozed_version != version {
+        return false;
+    }
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    now < deadline
+}
+
+/// The re-prompt deadline of a pending snooze, if one is recorded, regardless of version.
+/// Used by the background loop to wake up in time instead of waiting out the 24h interval.
+fn get_pending_snooze_deadline(app: &tauri::AppHandle) -> Option<u64> {
+    read_snoozed_update(app).map(|(_, deadline)| deadline)
+}
+
+fn save_snoozed_update(app: &tauri::AppHandle, version: &str) {
+    let Some(file_path) = get_snoozed_update_file(app) else {
+        return;
+    };
+
+    if let Some(parent) = file_path.parent() {
+        let _ = fs::create_dir_all(parent);
+    }
+
+    let deadline = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+        + get_update_snooze_interval().as_secs();
+
+    if let Err(e) = fs::write(&file_path, format!("{}\n{}", version, deadline)) {
+        log::warn!("Failed to save snoozed update: {}", e);
+    }
+}
+
+fn clear_snoozed_update(app: &tauri::AppHandle) {
+    if let Some(file_path) = get_snoozed_update_file(app) {
+        let _ = fs::remove_file(&file_path);
+    }
+}
+
+const DEFAULT_UPDATE_ENDPOINT: &str = "https://hackerai.co/api/updater/{{target}}/{{current_version}}";
+
+/// Ordered list of update endpoint templates, each tried in sequence until one
+/// returns a valid signed manifest. Sourced from `HACKERAI_UPDATE_ENDPOINTS`
+/// (comma-separated, same parsing style as `get_allowed_hosts`) with a single
+/// production default as the fallback.
+fn get_update_endpoints() -> Vec<String> {
+    match std::env::var("HACKERAI_UPDATE_ENDPOINTS") {
+        Ok(endpoints) => endpoints
+            .split(',')
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+            .collect(),
+        Err(_) => vec![DEFAULT_UPDATE_ENDPOINT.to_string()],
+    }
+}
+
+fn resolve_update_endpoint(template: &str, target: &str, current_version: &str) -> Option<url::Url> {
+    let resolved = template
+        .replace("{{target}}", target)
+        .replace("{{current_version}}", current_version);
+
+    match url::Url::parse(&resolved) {
+        Ok(url) => Some(url),
+        Err(e) => {
+            log::warn!("Invalid update endpoint {:?}: {}", resolved, e);
+            None
+        }
+    }
+}
+
 fn get_allowed_hosts() -> Vec<String> {
     match std::env::var("HACKERAI_ALLOWED_HOSTS") {
         Ok(hosts) => hosts.split(',').map(|s| s.trim().to_string()).collect(),
@@ -58,6 +240,75 @@ fn is_valid_token_format(token: &str) -> bool {
     token.len() == 64 && token.chars().all(|c| c.is_ascii_hexdigit())
 }
 
+const AUTH_STATE_TTL: Duration = Duration::from_secs(10 * 60); // 10 minutes
+
+fn get_auth_state_file(app: &tauri::AppHandle) -> Option<PathBuf> {
+    app.path().app_data_dir().ok().map(|dir| dir.join("auth_state"))
+}
+
+fn generate_auth_state() -> String {
+    let mut bytes = [0u8; 32];
+    rand::rngs::OsRng.fill_bytes(&mut bytes);
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b.iter()).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Generates and persists a one-time nonce for the login flow the frontend is about to
+/// start, so the eventual `hackerai://auth` deep link can be tied back to this request.
+#[tauri::command]
+fn start_auth_login(app: tauri::AppHandle) -> Result<String, String> {
+    let state = generate_auth_state();
+    let file_path = get_auth_state_file(&app).ok_or("app data directory unavailable")?;
+
+    if let Some(parent) = file_path.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+
+    let expiry = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+        + AUTH_STATE_TTL.as_secs();
+
+    fs::write(&file_path, format!("{}\n{}", state, expiry)).map_err(|e| e.to_string())?;
+
+    Ok(state)
+}
+
+/// Validates and consumes the `state` param from an inbound auth deep link. The stored
+/// nonce is deleted on any call (match or not) so it can never be replayed.
+fn consume_auth_state(app: &tauri::AppHandle, candidate: &str) -> bool {
+    let Some(file_path) = get_auth_state_file(app) else {
+        return false;
+    };
+
+    let Ok(content) = fs::read_to_string(&file_path) else {
+        return false;
+    };
+    let _ = fs::remove_file(&file_path);
+
+    let mut lines = content.lines();
+    let Some(stored_state) = lines.next() else {
+        return false;
+    };
+    let Some(expiry) = lines.next().and_then(|s| s.trim().parse::<u64>().ok()) else {
+        return false;
+    };
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+
+    now < expiry && constant_time_eq(stored_state.as_bytes(), candidate.as_bytes())
+}
+
 fn validate_origin(origin: &str) -> bool {
     match url::Url::parse(origin) {
         Ok(parsed) => {
@@ -72,6 +323,69 @@ fn validate_origin(origin: &str) -> bool {
     }
 }
 
+#[cfg(target_os = "linux")]
+fn register_linux_deep_link_handler() {
+    use std::process::Command;
+
+    let Some(home) = std::env::var_os("HOME") else {
+        log::warn!("Cannot register deep link handler: HOME is not set");
+        return;
+    };
+
+    let exec_path = match std::env::var("APPIMAGE") {
+        Ok(appimage_path) => PathBuf::from(appimage_path),
+        Err(_) => match std::env::current_exe() {
+            Ok(path) => path,
+            Err(e) => {
+                log::warn!("Cannot register deep link handler: failed to resolve current executable: {}", e);
+                return;
+            }
+        },
+    };
+
+    let applications_dir = PathBuf::from(home).join(".local/share/applications");
+    if let Err(e) = fs::create_dir_all(&applications_dir) {
+        log::warn!("Cannot register deep link handler: failed to create {:?}: {}", applications_dir, e);
+        return;
+    }
+
+    // Desktop Entry Exec values containing spaces must be quoted (AppImages are routinely
+    // downloaded into paths like "~/Downloads/My App-1.2.3.AppImage").
+    let escaped_exec_path = exec_path.display().to_string().replace('\\', "\\\\").replace('"', "\\\"");
+
+    let desktop_entry_path = applications_dir.join("hackerai.desktop");
+    let desktop_entry = format!(
+        "[Desktop Entry]\n\
+         Type=Application\n\
+         Name=HackerAI\n\
+         Exec=\"{}\" %u\n\
+         Terminal=false\n\
+         NoDisplay=true\n\
+         MimeType=x-scheme-handler/hackerai;\n",
+        escaped_exec_path
+    );
+
+    if let Err(e) = fs::write(&desktop_entry_path, desktop_entry) {
+        log::warn!("Failed to write desktop entry {:?}: {}", desktop_entry_path, e);
+        return;
+    }
+
+    match Command::new("update-desktop-database").arg(&applications_dir).status() {
+        Ok(status) if status.success() => log::info!("Updated desktop database"),
+        Ok(status) => log::warn!("update-desktop-database exited with status {}", status),
+        Err(e) => log::warn!("update-desktop-database not available, skipping: {}", e),
+    }
+
+    match Command::new("xdg-mime")
+        .args(["default", "hackerai.desktop", "x-scheme-handler/hackerai"])
+        .status()
+    {
+        Ok(status) if status.success() => log::info!("Registered hackerai:// scheme via xdg-mime"),
+        Ok(status) => log::warn!("xdg-mime exited with status {}", status),
+        Err(e) => log::warn!("xdg-mime not available, skipping scheme registration: {}", e),
+    }
+}
+
 fn handle_auth_deep_link(app: &tauri::AppHandle, url: &url::Url) {
     if url.scheme() != "hackerai" {
         return;
@@ -85,6 +399,13 @@ fn handle_auth_deep_link(app: &tauri::AppHandle, url: &url::Url) {
                     return;
                 }
 
+                let state = url.query_pairs().find(|(k, _)| k == "state").map(|(_, v)| v.to_string());
+                let state_valid = state.as_deref().is_some_and(|s| consume_auth_state(app, s));
+                if !state_valid {
+                    log::error!("Auth deep link rejected: missing, mismatched, or expired state");
+                    return;
+                }
+
                 if let Some(window) = app.get_webview_window("main") {
                     // Get and validate origin from deep link query params
                     let origin = url.query_pairs()
@@ -131,28 +452,88 @@ fn handle_auth_deep_link(app: &tauri::AppHandle, url: &url::Url) {
 async fn check_for_updates(app: tauri::AppHandle, silent: bool) {
     use tauri_plugin_dialog::{DialogExt, MessageDialogButtons, MessageDialogKind};
 
-    let updater = match app.updater() {
-        Ok(updater) => updater,
-        Err(e) => {
-            if silent {
-                log::warn!("Auto-update check failed to get updater: {}", e);
-            } else {
-                log::error!("Failed to get updater: {}", e);
-                let _ = app.dialog()
-                    .message(format!("Failed to check for updates: {}", e))
-                    .kind(MessageDialogKind::Error)
-                    .title("Update Error")
-                    .blocking_show();
+    let current_version = app.package_info().version.to_string();
+    let target = format!("{}-{}", std::env::consts::OS, std::env::consts::ARCH);
+
+    let mut check_result = None;
+    let mut last_error = None;
+    for template in get_update_endpoints() {
+        let Some(endpoint) = resolve_update_endpoint(&template, &target, &current_version) else {
+            continue;
+        };
+
+        // Bypass the plugin's own semver-greater check here: rollouts/rollbacks are
+        // entirely gated by `decide_update_install()` below, including intentional downgrades.
+        let updater = match app
+            .updater_builder()
+            .endpoints(vec![endpoint.clone()])
+            .map(|builder| builder.version_comparator(|_current, _candidate| true))
+            .and_then(|builder| builder.build())
+        {
+            Ok(updater) => updater,
+            Err(e) => {
+                log::warn!("Failed to build updater for endpoint {}: {}", endpoint, e);
+                last_error = Some(e.to_string());
+                continue;
+            }
+        };
+
+        match updater.check().await {
+            Ok(result) => {
+                check_result = Some(result);
+                break;
             }
-            return;
+            Err(e) => {
+                log::warn!("Update check against {} failed: {}", endpoint, e);
+                last_error = Some(e.to_string());
+            }
+        }
+    }
+
+    let Some(check_result) = check_result else {
+        let message = last_error.unwrap_or_else(|| "no valid update endpoints configured".to_string());
+        if silent {
+            log::warn!("Auto-update check failed on all endpoints: {}", message);
+        } else {
+            log::error!("Failed to check for updates on all endpoints: {}", message);
+            let _ = app.dialog()
+                .message(format!("Failed to check for updates: {}", message))
+                .kind(MessageDialogKind::Error)
+                .title("Update Error")
+                .blocking_show();
         }
+        return;
     };
 
-    match updater.check().await {
-        Ok(Some(update)) => {
+    match check_result {
+        Some(update) => {
             let version = update.version.clone();
             log::info!("Update available: {}", version);
 
+            let bucket = get_or_create_rollout_bucket(&app);
+            match decide_update_install(&current_version, &version, &update.raw_json, bucket) {
+                UpdateDecision::Install => {}
+                UpdateDecision::RejectedNotNewer => {
+                    log::info!(
+                        "Skipping update to {} (not newer than current version {} and downgrade not permitted)",
+                        version, current_version
+                    );
+                    return;
+                }
+                UpdateDecision::RejectedRolloutCohort => {
+                    log::info!(
+                        "Skipping update to {} (not selected for this device's rollout cohort)",
+                        version
+                    );
+                    return;
+                }
+            }
+
+            if silent && is_update_snoozed(&app, &version) {
+                log::info!("Skipping update to {} (snoozed by user, re-prompt deadline not reached)", version);
+                return;
+            }
+
             let should_update = app.dialog()
                 .message(format!(
                     "A new version ({}) is available. Would you like to update now?",
@@ -163,9 +544,40 @@ async fn check_for_updates(app: tauri::AppHandle, silent: bool) {
                 .buttons(MessageDialogButtons::OkCancel)
                 .blocking_show();
 
+            if !should_update {
+                log::info!("User declined update to version {}, snoozing re-prompt", version);
+                save_snoozed_update(&app, &version);
+            }
+
             if should_update {
                 log::info!("User accepted update to version {}", version);
-                if let Err(e) = update.download_and_install(|_, _| {}, || {}).await {
+                clear_snoozed_update(&app);
+
+                let progress_app = app.clone();
+                let mut downloaded: u64 = 0;
+                let mut last_emitted_percentage: u64 = 0;
+                if let Err(e) = update
+                    .download_and_install(
+                        move |chunk_length, content_length| {
+                            downloaded += chunk_length as u64;
+                            if let Some(total) = content_length {
+                                let percentage = ((downloaded as f64 / total as f64 * 100.0).min(100.0)) as u64;
+                                if percentage > last_emitted_percentage {
+                                    last_emitted_percentage = percentage;
+                                    let _ = progress_app.emit("update://progress", percentage);
+                                }
+                            }
+                        },
+                        {
+                            let installing_app = app.clone();
+                            move || {
+                                log::info!("Update download finished, installing...");
+                                let _ = installing_app.emit("update://installing", ());
+                            }
+                        },
+                    )
+                    .await
+                {
                     log::error!("Failed to install update: {}", e);
                     let _ = app.dialog()
                         .message(format!("Failed to install update: {}", e))
@@ -186,7 +598,7 @@ async fn check_for_updates(app: tauri::AppHandle, silent: bool) {
                 }
             }
         }
-        Ok(None) => {
+        None => {
             if silent {
                 log::info!("No updates available (auto-check)");
             } else {
@@ -198,18 +610,6 @@ async fn check_for_updates(app: tauri::AppHandle, silent: bool) {
                     .blocking_show();
             }
         }
-        Err(e) => {
-            if silent {
-                log::warn!("Auto-update check failed: {}", e);
-            } else {
-                log::error!("Failed to check for updates: {}", e);
-                let _ = app.dialog()
-                    .message(format!("Failed to check for updates: {}", e))
-                    .kind(MessageDialogKind::Error)
-                    .title("Update Error")
-                    .blocking_show();
-            }
-        }
     }
 }
 
@@ -223,6 +623,7 @@ pub fn run() {
         .plugin(tauri_plugin_deep_link::init())
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_dialog::init())
+        .invoke_handler(tauri::generate_handler![start_auth_login])
         .plugin(tauri_plugin_single_instance::init(|app, args, _cwd| {
             // Handle deep links passed as CLI args (Linux/Windows)
             log::info!("Single instance callback with args: {:?}", args);
@@ -255,6 +656,11 @@ pub fn run() {
                     }
                 }
 
+                // register_all() alone doesn't reliably claim the scheme for AppImage
+                // or non-installed Linux builds, so also own a .desktop entry directly.
+                #[cfg(target_os = "linux")]
+                register_linux_deep_link_handler();
+
                 let handle = app.handle().clone();
                 app.deep_link().on_open_url(move |event| {
                     let urls = event.urls();
@@ -272,11 +678,38 @@ pub fn run() {
                 save_update_check_timestamp(&handle);
                 check_for_updates(handle.clone(), true).await;
 
-                // Then check every hour if 24h has passed (for long-running sessions)
+                // Then check every hour if 24h has passed (for long-running sessions),
+                // or sooner if a declined update's snooze window is about to elapse.
                 loop {
-                    tokio::time::sleep(Duration::from_secs(60 * 60)).await;
-                    if should_check_for_updates(&handle) {
-                        log::info!("Running scheduled update check (24h interval)");
+                    let now = SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .unwrap_or_default()
+                        .as_secs();
+                    let sleep_duration = match get_pending_snooze_deadline(&handle) {
+                        Some(deadline) if deadline > now => {
+                            Duration::from_secs(deadline - now).min(Duration::from_secs(60 * 60))
+                        }
+                        Some(_) => Duration::from_secs(1),
+                        None => Duration::from_secs(60 * 60),
+                    };
+                    tokio::time::sleep(sleep_duration).await;
+
+                    let now = SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .unwrap_or_default()
+                        .as_secs();
+                    let snooze_elapsed = get_pending_snooze_deadline(&handle).is_some_and(|deadline| now >= deadline);
+
+                    if snooze_elapsed {
+                        // Re-offer once; declining again records a fresh snooze.
+                        clear_snoozed_update(&handle);
+                    }
+
+                    if should_check_for_updates(&handle) || snooze_elapsed {
+                        log::info!(
+                            "Running scheduled update check ({})",
+                            if snooze_elapsed { "snooze elapsed" } else { "24h interval" }
+                        );
                         save_update_check_timestamp(&handle);
                         check_for_updates(handle.clone(), true).await;
                     }